@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io::SeekFrom;
 use std::sync::Arc;
 
@@ -18,20 +19,36 @@ use av_format::{
     stream::Stream,
 };
 
+use crate::metadata::{parse_adtl, parse_cue, parse_info, CuePoint};
 use crate::parser::{
-    custom_error, get_data, header, parse_header_fmt, read_chunks_type, read_duration, skip_chunk,
-    ErrorKind, Format,
+    custom_error, get_data, header, parse_header_fmt, read_chunks_type, read_duration, read_size,
+    skip_chunk, skip_pad, Ds64, ErrorKind, Format, SizeMode,
 };
-use crate::{find_codec_from_wav_twocc, PCM_FLOAT_FORMAT_ID};
+use crate::{channel_map_from_mask, find_codec_from_wav_twocc, PCM_FLOAT_FORMAT_ID};
 
 #[derive(Debug, Clone, Default)]
 pub struct WavDemuxer {
     pub format: Format,
+    /// `LIST`/`INFO` tags, e.g. `"INAM" -> <title>`.
+    pub tags: HashMap<String, String>,
+    /// Cue points declared by the `cue ` chunk.
+    pub cue_points: Vec<CuePoint>,
+    /// Cue point labels from an `adtl`-type `LIST` chunk, keyed by cue id.
+    pub cue_labels: HashMap<u32, String>,
     data_pos: usize,
+    data_start: usize,
     data_end: usize,
+    /// Bytes of `data` consumed so far, relative to `data_start`. Unlike
+    /// `data_pos` — which `read_event` also reuses as the buffer-relative
+    /// advance to hand back as a `SeekFrom::Current` — this moves forward by
+    /// what's actually been read, or is set outright by `seek`, so it's safe
+    /// to turn into a pts.
+    stream_pos: u64,
     cname: &'static str,
     is_pcm: bool,
     duration: u64,
+    size_mode: SizeMode,
+    ds64: Option<Ds64>,
 }
 
 impl WavDemuxer {
@@ -39,12 +56,69 @@ impl WavDemuxer {
         Self::default()
     }
 
+    /// Unpacks a `data` chunk packet into interleaved samples according to
+    /// this stream's `(bits, byte_width)` layout. Divide the result length by
+    /// `format.channels` to recover per-channel samples.
+    pub fn unpack_samples(
+        &self,
+        data: &[u8],
+    ) -> std::result::Result<Vec<crate::sample::Sample>, crate::sample::UnsupportedSampleFormat>
+    {
+        let byte_width = if self.format.channels > 0 {
+            self.format.block_align / self.format.channels
+        } else {
+            self.format.block_align
+        };
+        crate::sample::unpack_samples(
+            data,
+            self.format.bits_per_sample,
+            byte_width,
+            self.format.effective_format_tag() == PCM_FLOAT_FORMAT_ID,
+        )
+    }
+
+    /// Seeks to `target_samples` (in the stream's timebase, i.e. sample
+    /// frames) for constant-bitrate content, returning the byte offset to
+    /// seek the underlying reader to. `pts` is recomputed from the new
+    /// `stream_pos` on the next [`Demuxer::read_event`] call.
+    ///
+    /// Returns an error if `avg_bytes_per_sec` is unknown or the content
+    /// isn't constant-bitrate, so the caller can fall back to streaming.
+    pub fn seek(&mut self, target_samples: u64) -> Result<SeekFrom> {
+        if !self.is_pcm || self.format.avg_bytes_per_sec == 0 || self.format.samples_per_sec == 0
+        {
+            return Err(Error::InvalidData);
+        }
+
+        let byte_offset = target_samples * u64::from(self.format.avg_bytes_per_sec)
+            / u64::from(self.format.samples_per_sec);
+
+        let mut offset = (self.data_start as u64 + byte_offset).clamp(
+            self.data_start as u64,
+            self.data_end as u64,
+        );
+
+        if self.format.block_align > 0 {
+            offset -= (offset - self.data_start as u64) % u64::from(self.format.block_align);
+        }
+
+        self.data_pos = offset as usize;
+        self.stream_pos = offset - self.data_start as u64;
+        Ok(SeekFrom::Start(offset))
+    }
+
     pub fn parse_headers<'a>(
         &mut self,
         input: &'a [u8],
     ) -> IResult<&'a [u8], (), crate::parser::Error<'a>> {
-        // Parse header and fmt chunk
-        let (mut i, format) = parse_header_fmt(input)?;
+        // Parse header, the ds64 chunk (RF64/BW64 only), and the fmt chunk
+        let (mut i, (_header, ds64, format)) = parse_header_fmt(input)?;
+        self.size_mode = if ds64.is_some() {
+            SizeMode::Rf64
+        } else {
+            SizeMode::Riff
+        };
+        self.ds64 = ds64;
 
         // Analyze fmt chunk
         self.analyze_fmt(format);
@@ -61,9 +135,19 @@ impl WavDemuxer {
                 }
                 b"data" => {
                     self.data_pos = input.offset(inp);
-                    self.data_end = self.data_pos + csize as usize;
+                    self.data_start = self.data_pos;
+                    self.stream_pos = 0;
+                    let data_size = read_size(
+                        self.size_mode,
+                        csize,
+                        self.ds64.as_ref().map(|ds64| ds64.data_size),
+                    );
+                    self.data_end = self.data_pos + data_size as usize;
 
-                    self.duration = if self.duration != 0 {
+                    let sample_count = self.ds64.as_ref().map(|ds64| ds64.sample_count);
+                    self.duration = if sample_count.unwrap_or(0) != 0 {
+                        sample_count.unwrap() * 1000 / u64::from(self.format.samples_per_sec)
+                    } else if self.duration != 0 {
                         (self.duration as u64) * 1000 / u64::from(self.format.samples_per_sec)
                     } else if self.format.avg_bytes_per_sec > 0 {
                         (self.data_end - self.data_pos) as u64 * 1000
@@ -74,14 +158,31 @@ impl WavDemuxer {
 
                     return Ok((inp, ()));
                 }
-                _ => skip_chunk(inp, csize as usize)?.0,
+                b"LIST" => {
+                    let (next, list_data) = skip_chunk(inp, csize as usize)?;
+                    if list_data.len() >= 4 {
+                        match &list_data[0..4] {
+                            b"INFO" => self.tags.extend(parse_info(&list_data[4..])),
+                            b"adtl" => self.cue_labels.extend(parse_adtl(&list_data[4..])),
+                            _ => {}
+                        }
+                    }
+                    skip_pad(next, csize)?.0
+                }
+                b"cue " => {
+                    let (next, cue_data) = skip_chunk(inp, csize as usize)?;
+                    self.cue_points = parse_cue(cue_data);
+                    skip_pad(next, csize)?.0
+                }
+                _ => skip_pad(skip_chunk(inp, csize as usize)?.0, csize)?.0,
             };
         }
         Ok((i, ()))
     }
 
     fn analyze_fmt(&mut self, format: Format) {
-        self.cname = find_codec_from_wav_twocc(format.format_tag).unwrap_or("unknown");
+        self.cname =
+            find_codec_from_wav_twocc(format.effective_format_tag()).unwrap_or("unknown");
         self.is_pcm = self.cname == "pcm";
         self.format = format;
         self.format.avg_bytes_per_sec = if self.is_pcm && self.format.avg_bytes_per_sec == 0 {
@@ -97,7 +198,7 @@ impl Demuxer for WavDemuxer {
         match self.parse_headers(buf.data()) {
             Ok((i, _)) => {
                 let soniton = if self.cname == "pcm" {
-                    if self.format.format_tag != PCM_FLOAT_FORMAT_ID {
+                    if self.format.effective_format_tag() != PCM_FLOAT_FORMAT_ID {
                         if self.format.bits_per_sample == 8 {
                             Soniton::new(8, false, false, false, false, false)
                         } else {
@@ -130,9 +231,15 @@ impl Demuxer for WavDemuxer {
                         true,
                     )
                 };
+                let map = match self.format.extensible {
+                    Some(ext) => {
+                        channel_map_from_mask(ext.channel_mask, self.format.channels as usize)
+                    }
+                    None => ChannelMap::default_map(self.format.channels as usize),
+                };
                 let audio_info = AudioInfo {
                     rate: self.format.samples_per_sec as usize,
-                    map: Some(ChannelMap::default_map(self.format.channels as usize)),
+                    map: Some(map),
                     format: Some(Arc::new(soniton)),
                 };
                 let stream = Stream {
@@ -169,7 +276,7 @@ impl Demuxer for WavDemuxer {
     fn read_event(&mut self, buf: &mut dyn Buffered) -> Result<(SeekFrom, Event)> {
         let pts = if self.format.avg_bytes_per_sec != 0 {
             Some(
-                self.data_pos as i64 * i64::from(self.format.samples_per_sec)
+                self.stream_pos as i64 * i64::from(self.format.samples_per_sec)
                     / i64::from(self.format.avg_bytes_per_sec),
             )
         } else {
@@ -200,6 +307,7 @@ impl Demuxer for WavDemuxer {
                     is_corrupted: false,
                 };
 
+                self.stream_pos += data.len() as u64;
                 self.data_pos = buf.data().offset(i);
                 let seek = SeekFrom::Current(self.data_pos as i64);
                 Ok((seek, Event::NewPacket(packet)))
@@ -268,4 +376,96 @@ mod tests {
             }
         }
     }
+
+    // Builds a minimal mono 16-bit PCM, 44.1kHz `RIFF`/`WAVE` file with
+    // `sample_count` silent frames of `data`.
+    fn pcm_wav(sample_count: u32) -> Vec<u8> {
+        let data_size = sample_count * 2;
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"RIFF");
+        buf.extend_from_slice(&(36 + data_size).to_le_bytes());
+        buf.extend_from_slice(b"WAVE");
+        buf.extend_from_slice(b"fmt ");
+        buf.extend_from_slice(&16u32.to_le_bytes());
+        buf.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        buf.extend_from_slice(&1u16.to_le_bytes()); // mono
+        buf.extend_from_slice(&44_100u32.to_le_bytes());
+        buf.extend_from_slice(&88_200u32.to_le_bytes());
+        buf.extend_from_slice(&2u16.to_le_bytes()); // block_align
+        buf.extend_from_slice(&16u16.to_le_bytes());
+        buf.extend_from_slice(b"data");
+        buf.extend_from_slice(&data_size.to_le_bytes());
+        buf.extend(std::iter::repeat(0u8).take(data_size as usize));
+        buf
+    }
+
+    #[test]
+    fn seek_lands_on_the_requested_sample() {
+        let wav = pcm_wav(44_100);
+        let mut demuxer = WavDemuxer::new();
+        demuxer.parse_headers(&wav).unwrap();
+
+        let offset = match demuxer.seek(22_050).unwrap() {
+            SeekFrom::Start(offset) => offset,
+            other => panic!("expected an absolute seek, got {:?}", other),
+        };
+        assert_eq!(offset, demuxer.data_start as u64 + 22_050 * 2);
+
+        let pts = demuxer.stream_pos as i64 * i64::from(demuxer.format.samples_per_sec)
+            / i64::from(demuxer.format.avg_bytes_per_sec);
+        assert_eq!(pts, 22_050);
+    }
+
+    // Builds a stereo 16-bit PCM, 44.1kHz `RIFF`/`WAVE` file with a `LIST`/
+    // `INFO` chunk before `data`, wide enough to push `data_start` past a
+    // single `read_event` block read (128 bytes).
+    fn pcm_wav_with_info(data_size: u32) -> Vec<u8> {
+        let mut info = Vec::new();
+        info.extend_from_slice(b"INFO");
+        info.extend_from_slice(b"INAM");
+        let mut text = vec![b'x'; 99];
+        text.push(0);
+        info.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        info.extend_from_slice(&text);
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"RIFF");
+        buf.extend_from_slice(&(36 + 8 + info.len() as u32 + data_size).to_le_bytes());
+        buf.extend_from_slice(b"WAVE");
+        buf.extend_from_slice(b"fmt ");
+        buf.extend_from_slice(&16u32.to_le_bytes());
+        buf.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        buf.extend_from_slice(&2u16.to_le_bytes()); // stereo
+        buf.extend_from_slice(&44_100u32.to_le_bytes());
+        buf.extend_from_slice(&176_400u32.to_le_bytes());
+        buf.extend_from_slice(&4u16.to_le_bytes()); // block_align
+        buf.extend_from_slice(&16u16.to_le_bytes());
+        buf.extend_from_slice(b"LIST");
+        buf.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&info);
+        buf.extend_from_slice(b"data");
+        buf.extend_from_slice(&data_size.to_le_bytes());
+        buf.extend(std::iter::repeat(0u8).take(data_size as usize));
+        buf
+    }
+
+    #[test]
+    fn read_event_pts_advances_past_metadata_before_data() {
+        let wav = pcm_wav_with_info(256);
+        let mut context = Context::new(WavDemuxer::new(), AccReader::new(Cursor::new(wav)));
+        context.read_headers().unwrap();
+        assert!(context.demuxer().data_start > 128);
+
+        let mut pts_values = Vec::new();
+        loop {
+            match context.read_event().unwrap() {
+                Event::NewPacket(packet) => pts_values.push(packet.t.pts.unwrap()),
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        // Two 128-byte packets (block_align=4): pts lands on 0, then 32
+        // samples in, never on the inflated `data_start`-relative value.
+        assert_eq!(pts_values, vec![0, 32]);
+    }
 }
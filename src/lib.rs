@@ -3,13 +3,72 @@
 //! To better understand the WAV format, read the
 //! <a href="http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/WAVE/WAVE.html" target="_blank">WAV Specification</a>.
 
+use av_data::audiosample::{ChannelMap, ChannelPosition};
+
 pub mod demuxer;
+pub mod metadata;
 pub mod muxer;
 pub mod parser;
+pub mod sample;
 
 // A special case for floating-point audio
 pub(crate) const PCM_FLOAT_FORMAT_ID: u16 = 0x0003;
 
+// `dwChannelMask` bit positions, in the order defined by WAVEFORMATEXTENSIBLE.
+static CHANNEL_MASK_POSITIONS: &[(u32, ChannelPosition)] = &[
+    (0x0001, ChannelPosition::FrontLeft),
+    (0x0002, ChannelPosition::FrontRight),
+    (0x0004, ChannelPosition::FrontCenter),
+    (0x0008, ChannelPosition::LFE),
+    (0x0010, ChannelPosition::BackLeft),
+    (0x0020, ChannelPosition::BackRight),
+    (0x0040, ChannelPosition::FrontLeftOfCenter),
+    (0x0080, ChannelPosition::FrontRightOfCenter),
+    (0x0100, ChannelPosition::BackCenter),
+    (0x0200, ChannelPosition::SideLeft),
+    (0x0400, ChannelPosition::SideRight),
+    (0x0800, ChannelPosition::TopCenter),
+    (0x1000, ChannelPosition::TopFrontLeft),
+    (0x2000, ChannelPosition::TopFrontCenter),
+    (0x4000, ChannelPosition::TopFrontRight),
+    (0x8000, ChannelPosition::TopBackLeft),
+    (0x10000, ChannelPosition::TopBackCenter),
+    (0x20000, ChannelPosition::TopBackRight),
+];
+
+/// Translates a WAVEFORMATEXTENSIBLE `dwChannelMask` into an explicit
+/// `ChannelMap`. Falls back to the generic default layout when the mask
+/// doesn't carry exactly as many positions as there are channels.
+pub(crate) fn channel_map_from_mask(mask: u32, channels: usize) -> ChannelMap {
+    if mask.count_ones() as usize != channels {
+        return ChannelMap::default_map(channels);
+    }
+
+    let mut map = ChannelMap::new();
+    for (bit, position) in CHANNEL_MASK_POSITIONS {
+        if mask & bit != 0 {
+            map.add_position(*position);
+        }
+    }
+    map
+}
+
+/// The channel mask implied by a plain `fmt ` chunk's channel count: used to
+/// decide whether a source's channel mask is "non-standard" enough to
+/// warrant an EXTENSIBLE `fmt ` chunk, and to synthesize one for a
+/// multichannel source that didn't carry a mask of its own. `0` means the
+/// channel count has no conventional speaker layout to fall back on.
+pub(crate) fn default_channel_mask(channels: u16) -> u32 {
+    match channels {
+        1 => 0x0004,                         // FC
+        2 => 0x0003,                         // FL FR
+        4 => 0x0033,                         // FL FR BL BR (quad)
+        6 => 0x003F,                         // FL FR FC LFE BL BR (5.1)
+        8 => 0x063F,                         // 5.1 + SL SR (7.1)
+        _ => 0,
+    }
+}
+
 static WAV_CODEC_REGISTER: &[(u16, &str)] = &[
     (0x0000, "unknown"),
     (0x0001, "pcm"),
@@ -30,3 +89,24 @@ pub(crate) fn find_codec_from_wav_twocc(tcc: u16) -> Option<&'static str> {
         .find(|(twocc, _)| *twocc == tcc)
         .map(|(_, name)| *name)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_channel_mask_matches_common_layouts() {
+        assert_eq!(default_channel_mask(1), 0x0004);
+        assert_eq!(default_channel_mask(2), 0x0003);
+        assert_eq!(default_channel_mask(6), 0x003F);
+        assert_eq!(default_channel_mask(5), 0);
+    }
+
+    #[test]
+    fn channel_map_from_mask_falls_back_on_count_mismatch() {
+        // A stereo mask claiming five channels doesn't carry enough
+        // positions, so this must fall back to the generic default map
+        // instead of panicking while filling it in.
+        let _ = channel_map_from_mask(0x0003, 5);
+    }
+}
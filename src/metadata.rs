@@ -0,0 +1,155 @@
+//! `LIST`/`INFO` tag and `cue ` chunk parsing.
+//!
+//! `WavDemuxer::parse_headers` otherwise skips every chunk it doesn't
+//! recognize via `skip_chunk`, discarding this container metadata. The
+//! functions here turn the raw chunk payloads into the string-keyed tag map
+//! and cue point list exposed on `WavDemuxer`.
+
+use std::collections::HashMap;
+
+/// A cue point from a WAV `cue ` chunk: an id and its sample-frame position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CuePoint {
+    pub id: u32,
+    pub position: u32,
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Parses a `cue ` chunk's payload into its list of cue points.
+pub(crate) fn parse_cue(data: &[u8]) -> Vec<CuePoint> {
+    let count = match read_u32_le(data, 0) {
+        Some(count) => count as usize,
+        None => return Vec::new(),
+    };
+
+    (0..count)
+        .filter_map(|i| {
+            // id(4) + position(4) + fccChunk(4) + chunkStart(4) + blockStart(4) + sampleOffset(4)
+            let base = 4 + i * 24;
+            let id = read_u32_le(data, base)?;
+            let position = read_u32_le(data, base + 20)?;
+            Some(CuePoint { id, position })
+        })
+        .collect()
+}
+
+/// Parses an `INFO`-type `LIST` chunk's payload (past the `INFO` tag) into
+/// a string-keyed map, e.g. `"INAM" -> <title>`.
+pub(crate) fn parse_info(data: &[u8]) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+    let mut i = 0;
+
+    while i + 8 <= data.len() {
+        let id = String::from_utf8_lossy(&data[i..i + 4]).into_owned();
+        let size = match read_u32_le(data, i + 4) {
+            Some(size) => size as usize,
+            None => break,
+        };
+        let start = i + 8;
+        let end = start + size;
+        if end > data.len() {
+            break;
+        }
+
+        let value = String::from_utf8_lossy(&data[start..end])
+            .trim_end_matches('\0')
+            .to_owned();
+        tags.insert(id, value);
+
+        i = end + (size % 2); // sub-chunks are padded to an even length
+    }
+
+    tags
+}
+
+/// Parses an `adtl`-type `LIST` chunk's payload into cue point labels
+/// (`labl`/`note` sub-chunks), keyed by cue point id.
+pub(crate) fn parse_adtl(data: &[u8]) -> HashMap<u32, String> {
+    let mut labels = HashMap::new();
+    let mut i = 0;
+
+    while i + 8 <= data.len() {
+        let id = &data[i..i + 4];
+        let size = match read_u32_le(data, i + 4) {
+            Some(size) => size as usize,
+            None => break,
+        };
+        let start = i + 8;
+        let end = start + size;
+        if end > data.len() {
+            break;
+        }
+
+        if (id == b"labl" || id == b"note") && size >= 4 {
+            if let Some(cue_id) = read_u32_le(data, start) {
+                let text = String::from_utf8_lossy(&data[start + 4..end])
+                    .trim_end_matches('\0')
+                    .to_owned();
+                labels.insert(cue_id, text);
+            }
+        }
+
+        i = end + (size % 2);
+    }
+
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cue_reads_id_and_position() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&2u32.to_le_bytes()); // count
+        for (id, position) in [(1u32, 0u32), (2u32, 44_100u32)] {
+            data.extend_from_slice(&id.to_le_bytes());
+            data.extend_from_slice(&position.to_le_bytes());
+            data.extend_from_slice(b"data"); // fccChunk
+            data.extend_from_slice(&0u32.to_le_bytes()); // chunkStart
+            data.extend_from_slice(&0u32.to_le_bytes()); // blockStart
+            data.extend_from_slice(&position.to_le_bytes()); // sampleOffset
+        }
+
+        let points = parse_cue(&data);
+        assert_eq!(
+            points,
+            vec![
+                CuePoint { id: 1, position: 0 },
+                CuePoint {
+                    id: 2,
+                    position: 44_100
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_info_reads_padded_subchunks() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"INAM");
+        data.extend_from_slice(&5u32.to_le_bytes());
+        data.extend_from_slice(b"song\0"); // odd length, padded below
+        data.push(0);
+
+        let tags = parse_info(&data);
+        assert_eq!(tags.get("INAM").map(String::as_str), Some("song"));
+    }
+
+    #[test]
+    fn parse_adtl_reads_labels_keyed_by_cue_id() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"labl");
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(b"in\0\0");
+
+        let labels = parse_adtl(&data);
+        assert_eq!(labels.get(&1).map(String::as_str), Some("in"));
+    }
+}
@@ -1,16 +1,40 @@
+use std::collections::HashMap;
 use std::io::{Seek, SeekFrom, Write};
 use std::sync::Arc;
 
 use av_data::{packet::Packet, value::Value};
 use av_format::{common::GlobalInfo, error::*, muxer::*};
 
+use crate::metadata::CuePoint;
 use crate::parser::Format;
-use crate::{find_codec_from_wav_twocc, PCM_FLOAT_FORMAT_ID};
+use crate::{default_channel_mask, find_codec_from_wav_twocc, PCM_FLOAT_FORMAT_ID};
+
+// KSDATAFORMAT_SUBTYPE_* GUIDs share this suffix; only the first two bytes
+// (the sub-format tag) vary.
+const SUBFORMAT_GUID_SUFFIX: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+fn subformat_guid(tag: u16) -> [u8; 16] {
+    let mut guid = [0u8; 16];
+    guid[..2].copy_from_slice(&tag.to_le_bytes());
+    guid[2..].copy_from_slice(&SUBFORMAT_GUID_SUFFIX);
+    guid
+}
+
+// Offset of the reserved `ds64`/`JUNK` chunk id, right after the 12-byte
+// `RIFF....WAVE` header.
+const DS64_POS: u64 = 12;
+// riffSize(8) + dataSize(8) + sampleCount(8) + tableLength(4), no table.
+const DS64_CHUNK_SIZE: usize = 28;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct WavMuxer {
     format: Format,
     data_pos: u64,
+    tags: HashMap<String, String>,
+    cue_points: Vec<CuePoint>,
+    cue_labels: HashMap<u32, String>,
 }
 
 impl WavMuxer {
@@ -18,13 +42,148 @@ impl WavMuxer {
         Self {
             data_pos: 0,
             format,
+            tags: HashMap::new(),
+            cue_points: Vec::new(),
+            cue_labels: HashMap::new(),
         }
     }
 
-    fn patch_size<W: Write>(bw: &mut Writer<W>, pos: u64) -> Result<()> {
-        let size = bw.position() as u64 - pos;
-        bw.seek(SeekFrom::Current(-((size + 4) as i64)))?;
-        bw.write_all(&(size as u32).to_le_bytes())?;
+    /// Packs interleaved samples into raw PCM bytes according to this
+    /// stream's `(bits, byte_width)` layout, ready to hand to
+    /// [`Muxer::write_packet`] inside a [`Packet`].
+    pub fn pack_samples(
+        &self,
+        samples: &[crate::sample::Sample],
+    ) -> std::result::Result<Vec<u8>, crate::sample::UnsupportedSampleFormat> {
+        let byte_width = if self.format.channels > 0 {
+            self.format.block_align / self.format.channels
+        } else {
+            self.format.block_align
+        };
+        crate::sample::pack_samples(
+            samples,
+            self.format.bits_per_sample,
+            byte_width,
+            self.format.effective_format_tag() == PCM_FLOAT_FORMAT_ID,
+        )
+    }
+
+    // Writes `size` into the 4-byte chunk-size field at `field_pos`, leaving
+    // the stream positioned back at the end.
+    fn patch_size_at<W: Write>(bw: &mut Writer<W>, field_pos: u64, size: u32) -> Result<()> {
+        bw.seek(SeekFrom::Start(field_pos))?;
+        bw.write_all(&size.to_le_bytes())?;
+        bw.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// Appends the `LIST`/`INFO` tag chunk, the `cue ` chunk, and an
+    /// `adtl`-type `LIST` chunk for cue labels, whichever are non-empty.
+    fn write_metadata_chunks<W: Write>(&self, out: &mut Writer<W>) -> Result<()> {
+        if !self.tags.is_empty() {
+            let mut tags: Vec<_> = self.tags.iter().collect();
+            tags.sort_by_key(|(key, _)| key.as_str());
+
+            let mut info = Vec::new();
+            info.extend_from_slice(b"INFO");
+            for (key, value) in tags {
+                let mut key_bytes = *b"    ";
+                let n = key.len().min(4);
+                key_bytes[..n].copy_from_slice(&key.as_bytes()[..n]);
+                info.extend_from_slice(&key_bytes);
+
+                let mut text = value.clone().into_bytes();
+                text.push(0);
+                // `ckSize` is the unpadded data length; the word-alignment
+                // pad byte below must not be counted in it.
+                let text_len = text.len();
+                if text_len % 2 != 0 {
+                    text.push(0);
+                }
+                info.write_all(&(text_len as u32).to_le_bytes())?;
+                info.extend_from_slice(&text);
+            }
+
+            out.write_all(b"LIST")?;
+            out.write_all(&(info.len() as u32).to_le_bytes())?;
+            out.write_all(&info)?;
+        }
+
+        if !self.cue_points.is_empty() {
+            let mut cue = Vec::new();
+            cue.write_all(&(self.cue_points.len() as u32).to_le_bytes())?;
+            for point in &self.cue_points {
+                cue.write_all(&point.id.to_le_bytes())?;
+                cue.write_all(&point.position.to_le_bytes())?;
+                cue.extend_from_slice(b"data");
+                cue.write_all(&0u32.to_le_bytes())?; // chunkStart
+                cue.write_all(&0u32.to_le_bytes())?; // blockStart
+                cue.write_all(&point.position.to_le_bytes())?; // sampleOffset
+            }
+
+            out.write_all(b"cue ")?;
+            out.write_all(&(cue.len() as u32).to_le_bytes())?;
+            out.write_all(&cue)?;
+        }
+
+        if !self.cue_labels.is_empty() {
+            let mut cue_labels: Vec<_> = self.cue_labels.iter().collect();
+            cue_labels.sort_by_key(|(id, _)| **id);
+
+            let mut adtl = Vec::new();
+            adtl.extend_from_slice(b"adtl");
+            for (id, label) in cue_labels {
+                let mut text = label.clone().into_bytes();
+                text.push(0);
+                let text_len = text.len();
+                if text_len % 2 != 0 {
+                    text.push(0);
+                }
+                adtl.extend_from_slice(b"labl");
+                adtl.write_all(&((4 + text_len) as u32).to_le_bytes())?;
+                adtl.write_all(&id.to_le_bytes())?;
+                adtl.extend_from_slice(&text);
+            }
+
+            out.write_all(b"LIST")?;
+            out.write_all(&(adtl.len() as u32).to_le_bytes())?;
+            out.write_all(&adtl)?;
+        }
+
+        Ok(())
+    }
+
+    // Rewrites the reserved header as RF64/BW64: the `RIFF` magic becomes
+    // `RF64`, the placeholder chunk becomes a real `ds64` carrying the 64-bit
+    // sizes, and the 32-bit `riffSize`/`dataSize` fields are set to the
+    // `0xFFFFFFFF` sentinel.
+    fn patch_rf64<W: Write>(
+        &self,
+        bw: &mut Writer<W>,
+        riff_size: u64,
+        data_size: u64,
+    ) -> Result<()> {
+        let sample_count = if self.format.block_align > 0 {
+            data_size / u64::from(self.format.block_align)
+        } else {
+            0
+        };
+
+        bw.seek(SeekFrom::Start(0))?;
+        bw.write_all(b"RF64")?;
+        bw.write_all(&u32::MAX.to_le_bytes())?;
+
+        bw.seek(SeekFrom::Start(DS64_POS))?;
+        bw.write_all(b"ds64")?;
+        bw.write_all(&(DS64_CHUNK_SIZE as u32).to_le_bytes())?;
+        bw.write_all(&riff_size.to_le_bytes())?;
+        bw.write_all(&data_size.to_le_bytes())?;
+        bw.write_all(&sample_count.to_le_bytes())?;
+        bw.write_all(&0u32.to_le_bytes())?; // no chunk-size override table
+
+        bw.seek(SeekFrom::Start(self.data_pos - 4))?;
+        bw.write_all(&u32::MAX.to_le_bytes())?;
+
         bw.seek(SeekFrom::End(0))?;
         Ok(())
     }
@@ -42,15 +201,16 @@ impl Muxer for WavMuxer {
             return Err(Error::InvalidData);
         }
 
-        let codec_name = find_codec_from_wav_twocc(self.format.format_tag).unwrap_or("unknown");
+        let codec_name =
+            find_codec_from_wav_twocc(self.format.effective_format_tag()).unwrap_or("unknown");
         let twocc = if codec_name == "pcm" {
-            if self.format.format_tag != PCM_FLOAT_FORMAT_ID {
+            if self.format.effective_format_tag() != PCM_FLOAT_FORMAT_ID {
                 0x0001
             } else {
                 PCM_FLOAT_FORMAT_ID
             }
         } else {
-            self.format.format_tag
+            self.format.effective_format_tag()
         };
 
         let avg_bytes_per_sec = if codec_name == "pcm" {
@@ -62,18 +222,75 @@ impl Muxer for WavMuxer {
             0
         };
 
+        // Use EXTENSIBLE when the source reported a channel mask that isn't
+        // the implied default for a plain mono/stereo `fmt ` chunk, or
+        // whenever there are too many channels for a plain `fmt ` chunk to
+        // describe unambiguously. Synthesize a mask in the latter case if the
+        // source didn't carry one; if there's no conventional layout for
+        // this channel count either, fall back to a plain `fmt ` rather than
+        // claim an all-zero, meaningless speaker layout.
+        let channel_mask = self.format.extensible.map(|ext| ext.channel_mask).or_else(|| {
+            let mask = default_channel_mask(self.format.channels);
+            (mask != 0).then_some(mask)
+        });
+        let extensible = channel_mask.filter(|&mask| {
+            self.format.channels > 2 || mask != default_channel_mask(self.format.channels)
+        });
+
         let mut buf = Vec::new();
-        buf.extend_from_slice(b"RIFF\0\0\0\0WAVEfmt ");
-        buf.write_all(&((if edata_len == 0 { 16 } else { 18 + edata_len }) as u32).to_le_bytes())?;
-        buf.write_all(&twocc.to_le_bytes())?;
-        buf.write_all(&self.format.channels.to_le_bytes())?;
-        buf.write_all(&self.format.samples_per_sec.to_le_bytes())?;
-        buf.write_all(&avg_bytes_per_sec.to_le_bytes())?;
-        buf.write_all(&self.format.block_align.to_le_bytes())?;
-        buf.write_all(&self.format.bits_per_sample.to_le_bytes())?;
-        if let Some(ref edata_buf) = self.format.edata {
-            buf.write_all(&(edata_len as u16).to_le_bytes())?;
-            buf.extend_from_slice(edata_buf);
+        buf.extend_from_slice(b"RIFF\0\0\0\0WAVE");
+        // Reserve room for a `ds64` chunk up front: if the payload turns out
+        // to exceed `u32::MAX` we rewrite this as RF64/BW64 in `write_trailer`
+        // without disturbing any byte offset written after it. Until then it
+        // is just an unrecognized chunk that decoders skip.
+        buf.extend_from_slice(b"JUNK");
+        buf.write_all(&(DS64_CHUNK_SIZE as u32).to_le_bytes())?;
+        buf.extend_from_slice(&[0u8; DS64_CHUNK_SIZE]);
+        buf.extend_from_slice(b"fmt ");
+
+        if let Some(channel_mask) = extensible {
+            buf.write_all(&40u32.to_le_bytes())?;
+            buf.write_all(&crate::parser::WAVE_FORMAT_EXTENSIBLE.to_le_bytes())?;
+            buf.write_all(&self.format.channels.to_le_bytes())?;
+            buf.write_all(&self.format.samples_per_sec.to_le_bytes())?;
+            buf.write_all(&avg_bytes_per_sec.to_le_bytes())?;
+            buf.write_all(&self.format.block_align.to_le_bytes())?;
+            buf.write_all(&self.format.bits_per_sample.to_le_bytes())?;
+            buf.write_all(&22u16.to_le_bytes())?;
+            buf.write_all(&self.format.bits_per_sample.to_le_bytes())?;
+            buf.write_all(&channel_mask.to_le_bytes())?;
+            buf.extend_from_slice(&subformat_guid(twocc));
+        } else {
+            // `edata` holds the source's EXTENSIBLE extension bytes when it
+            // had one; those don't apply to a plain `fmt ` chunk, so only
+            // carry them over for a source that wasn't EXTENSIBLE to begin
+            // with.
+            let plain_edata = self
+                .format
+                .extensible
+                .is_none()
+                .then(|| self.format.edata.as_ref())
+                .flatten();
+            let plain_edata_len = plain_edata.map(|buf| buf.len()).unwrap_or(0);
+
+            buf.write_all(
+                &((if plain_edata_len == 0 {
+                    16
+                } else {
+                    18 + plain_edata_len
+                }) as u32)
+                    .to_le_bytes(),
+            )?;
+            buf.write_all(&twocc.to_le_bytes())?;
+            buf.write_all(&self.format.channels.to_le_bytes())?;
+            buf.write_all(&self.format.samples_per_sec.to_le_bytes())?;
+            buf.write_all(&avg_bytes_per_sec.to_le_bytes())?;
+            buf.write_all(&self.format.block_align.to_le_bytes())?;
+            buf.write_all(&self.format.bits_per_sample.to_le_bytes())?;
+            if let Some(edata_buf) = plain_edata {
+                buf.write_all(&(plain_edata_len as u16).to_le_bytes())?;
+                buf.extend_from_slice(edata_buf);
+            }
         }
         buf.extend_from_slice(b"data\0\0\0\0");
 
@@ -89,8 +306,24 @@ impl Muxer for WavMuxer {
     }
 
     fn write_trailer<W: Write>(&mut self, out: &mut Writer<W>) -> Result<()> {
-        Self::patch_size(out, self.data_pos)?;
-        Self::patch_size(out, 8)?;
+        // Patch the `data` chunk size now, before any trailing metadata
+        // chunks are appended below.
+        let data_size = out.position() as u64 - self.data_pos;
+        Self::patch_size_at(
+            out,
+            self.data_pos - 4,
+            data_size.min(u64::from(u32::MAX)) as u32,
+        )?;
+
+        self.write_metadata_chunks(out)?;
+
+        let riff_size = out.position() as u64 - 8;
+
+        if data_size > u64::from(u32::MAX) || riff_size > u64::from(u32::MAX) {
+            self.patch_rf64(out, riff_size, data_size)?;
+        } else {
+            Self::patch_size_at(out, 4, riff_size as u32)?;
+        }
         Ok(())
     }
 
@@ -98,7 +331,19 @@ impl Muxer for WavMuxer {
         Ok(())
     }
 
-    fn set_option<'a>(&mut self, _key: &str, _val: Value<'a>) -> Result<()> {
+    fn set_option<'a>(&mut self, key: &str, val: Value<'a>) -> Result<()> {
+        match (key, val) {
+            ("cue_points", Value::Bytes(bytes)) => {
+                self.cue_points = crate::metadata::parse_cue(&bytes);
+            }
+            ("cue_labels", Value::Bytes(bytes)) => {
+                self.cue_labels = crate::metadata::parse_adtl(&bytes);
+            }
+            (key, Value::Str(value)) => {
+                self.tags.insert(key.to_owned(), value.into_owned());
+            }
+            _ => {}
+        }
         Ok(())
     }
 }
@@ -1,7 +1,9 @@
 use nom::{
+    branch::alt,
     bytes::complete::{tag, take},
     combinator::{cond, map, verify},
-    number::complete::{le_u16, le_u32},
+    multi::count,
+    number::complete::{le_u16, le_u32, le_u64},
     sequence::{pair, tuple},
     Err, IResult,
 };
@@ -41,17 +43,29 @@ pub(crate) fn custom_error(input: &[u8], code: u8) -> Error {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RiffFormat {
+    Riff,
+    Rf64,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Header<'a> {
     magic1: &'a [u8],
     pub file_size: u32,
     magic2: &'a [u8],
+    format: RiffFormat,
 }
 
 pub(crate) fn header(input: &[u8]) -> IResult<&[u8], Header, Error> {
     map(
-        tuple((tag(b"RIFF"), le_u32, tag(b"WAVE"))),
+        tuple((alt((tag(b"RIFF"), tag(b"RF64"))), le_u32, tag(b"WAVE"))),
         |(magic1, file_size, magic2)| Header {
+            format: if magic1 == b"RF64" {
+                RiffFormat::Rf64
+            } else {
+                RiffFormat::Riff
+            },
             magic1,
             file_size,
             magic2,
@@ -59,6 +73,100 @@ pub(crate) fn header(input: &[u8]) -> IResult<&[u8], Header, Error> {
     )(input)
 }
 
+/// The `ds64` chunk that an RF64/BW64 stream carries immediately after the
+/// `RIFF`/`WAVE` header, providing 64-bit replacements for the `riffSize`,
+/// `dataSize`, and `sampleCount` fields whenever those 32-bit chunk sizes
+/// are written as the `0xFFFFFFFF` sentinel.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Ds64 {
+    pub(crate) riff_size: u64,
+    pub(crate) data_size: u64,
+    pub(crate) sample_count: u64,
+    pub(crate) table: Vec<([u8; 4], u64)>,
+}
+
+fn parse_ds64(input: &[u8], chunk_size: usize) -> IResult<&[u8], Ds64, Error> {
+    let (i, (riff_size, data_size, sample_count, table_length)) =
+        tuple((le_u64, le_u64, le_u64, le_u32))(input)?;
+    let (i, table) = count(
+        map(pair(take(4usize), le_u64), |(id, size): (&[u8], u64)| {
+            let mut chunk_id = [0u8; 4];
+            chunk_id.copy_from_slice(id);
+            (chunk_id, size)
+        }),
+        table_length as usize,
+    )(i)?;
+
+    // Skip any padding left after the table, in case `chunk_size` is larger
+    // than the fields we know how to interpret.
+    let read = 28 + table_length as usize * 12;
+    let (i, _) = cond(chunk_size > read, take(chunk_size.saturating_sub(read)))(i)?;
+
+    Ok((
+        i,
+        Ds64 {
+            riff_size,
+            data_size,
+            sample_count,
+            table,
+        },
+    ))
+}
+
+/// Distinguishes a plain RIFF/WAVE stream from an RF64/BW64 one, so that a
+/// 32-bit chunk size can be resolved against the `ds64` chunk when needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum SizeMode {
+    #[default]
+    Riff,
+    Rf64,
+}
+
+/// Resolves a chunk size, substituting the matching 64-bit field from `ds64`
+/// whenever the 32-bit size is the `0xFFFFFFFF` RF64/BW64 sentinel.
+pub(crate) fn read_size(mode: SizeMode, raw: u32, wide: Option<u64>) -> u64 {
+    match mode {
+        SizeMode::Riff => u64::from(raw),
+        SizeMode::Rf64 if raw == u32::MAX => wide.unwrap_or(u64::MAX),
+        SizeMode::Rf64 => u64::from(raw),
+    }
+}
+
+/// `wFormatTag` value marking a WAVEFORMATEXTENSIBLE `fmt ` chunk, whose real
+/// codec lives in [`Extensible::sub_format_tag`] instead.
+pub(crate) const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// The extension fields of a WAVEFORMATEXTENSIBLE `fmt ` chunk: the number of
+/// valid bits per stored sample, the speaker channel mask, and the sub-format
+/// GUID whose first two bytes carry the effective format tag.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Extensible {
+    pub valid_bits_per_sample: u16,
+    pub channel_mask: u32,
+    pub sub_format: [u8; 16],
+}
+
+impl Extensible {
+    pub fn sub_format_tag(&self) -> u16 {
+        u16::from_le_bytes([self.sub_format[0], self.sub_format[1]])
+    }
+}
+
+fn parse_extensible(edata: &[u8]) -> Option<Extensible> {
+    if edata.len() < 22 {
+        return None;
+    }
+    let valid_bits_per_sample = u16::from_le_bytes([edata[0], edata[1]]);
+    let channel_mask = u32::from_le_bytes([edata[2], edata[3], edata[4], edata[5]]);
+    let mut sub_format = [0u8; 16];
+    sub_format.copy_from_slice(&edata[6..22]);
+    Some(Extensible {
+        valid_bits_per_sample,
+        channel_mask,
+        sub_format,
+    })
+}
+
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct Format {
     pub format_tag: u16,
@@ -68,6 +176,18 @@ pub struct Format {
     pub block_align: u16,
     pub bits_per_sample: u16,
     pub edata: Option<Vec<u8>>,
+    pub extensible: Option<Extensible>,
+}
+
+impl Format {
+    /// The codec's real format tag: the sub-format tag for EXTENSIBLE
+    /// streams, or `format_tag` itself otherwise.
+    pub fn effective_format_tag(&self) -> u16 {
+        self.extensible
+            .as_ref()
+            .map(Extensible::sub_format_tag)
+            .unwrap_or(self.format_tag)
+    }
 }
 
 fn extradata(chunk_size: usize) -> impl Fn(&[u8]) -> IResult<&[u8], Option<Vec<u8>>, Error> {
@@ -104,21 +224,61 @@ fn parse_fmt(input: &[u8]) -> IResult<&[u8], Format, Error> {
                 bits_per_sample(chunk_size as usize),
                 extradata(chunk_size as usize),
             )),
-            |t| Format {
-                format_tag: t.0,
-                channels: t.1,
-                samples_per_sec: t.2,
-                avg_bytes_per_sec: t.3,
-                block_align: t.4,
-                bits_per_sample: t.5,
-                edata: t.6,
+            |t| {
+                let extensible = if t.0 == WAVE_FORMAT_EXTENSIBLE {
+                    t.6.as_deref().and_then(parse_extensible)
+                } else {
+                    None
+                };
+                Format {
+                    format_tag: t.0,
+                    channels: t.1,
+                    samples_per_sec: t.2,
+                    avg_bytes_per_sec: t.3,
+                    block_align: t.4,
+                    bits_per_sample: t.5,
+                    edata: t.6,
+                    extensible,
+                }
             },
         )(i)
     })
 }
 
-pub(crate) fn parse_header_fmt(input: &[u8]) -> IResult<&[u8], Format, Error> {
-    pair(header, parse_fmt)(input).map(|(i, (_, format))| (i, format))
+/// Parses the `RIFF`/`RF64` header and the `fmt ` chunk, skipping any
+/// reserved or unrecognized chunks in between (an RF64/BW64 `ds64` chunk, or
+/// a muxer's `JUNK` placeholder) rather than assuming `fmt ` is the very next
+/// chunk.
+pub(crate) fn parse_header_fmt(
+    input: &[u8],
+) -> IResult<&[u8], (Header, Option<Ds64>, Format), Error> {
+    let (mut i, header) = header(input)?;
+    let mut ds64 = None;
+
+    loop {
+        let (next, (ctype, csize)) = read_chunks_type(i)?;
+        if ctype == b"fmt " {
+            break;
+        }
+
+        // Chunks are padded to an even length; `csize` doesn't include that
+        // trailing pad byte.
+        let padded = csize as usize + (csize as usize & 1);
+        i = if ctype == b"ds64" {
+            let (after, parsed) = parse_ds64(next, csize as usize)?;
+            ds64 = Some(parsed);
+            if csize % 2 == 1 {
+                take(1usize)(after)?.0
+            } else {
+                after
+            }
+        } else {
+            skip_chunk(next, padded)?.0
+        };
+    }
+
+    let (i, format) = parse_fmt(i)?;
+    Ok((i, (header, ds64, format)))
 }
 
 pub(crate) fn read_duration(input: &[u8]) -> IResult<&[u8], u32, Error> {
@@ -129,6 +289,12 @@ pub(crate) fn skip_chunk(input: &[u8], chunk_size: usize) -> IResult<&[u8], &[u8
     take(chunk_size)(input)
 }
 
+/// Consumes the RIFF word-alignment pad byte that follows an odd-sized
+/// chunk's declared `chunk_size`, if any.
+pub(crate) fn skip_pad(input: &[u8], chunk_size: u32) -> IResult<&[u8], &[u8], Error> {
+    take(chunk_size as usize & 1)(input)
+}
+
 pub(crate) fn get_data(input: &[u8], data_size: usize) -> IResult<&[u8], &[u8], Error> {
     take(data_size)(input)
 }
@@ -136,3 +302,37 @@ pub(crate) fn get_data(input: &[u8], data_size: usize) -> IResult<&[u8], &[u8],
 pub(crate) fn read_chunks_type(input: &[u8]) -> IResult<&[u8], (&[u8], u32), Error> {
     pair(take(4usize), le_u32)(input)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_size_resolves_rf64_sentinel_against_wide() {
+        assert_eq!(read_size(SizeMode::Rf64, u32::MAX, Some(1 << 40)), 1 << 40);
+        assert_eq!(read_size(SizeMode::Rf64, 1234, Some(1 << 40)), 1234);
+    }
+
+    #[test]
+    fn read_size_riff_mode_ignores_wide() {
+        assert_eq!(read_size(SizeMode::Riff, u32::MAX, Some(1 << 40)), u32::MAX as u64);
+    }
+
+    #[test]
+    fn parse_ds64_reads_sizes_and_table() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(1u64 << 33).to_le_bytes()); // riff_size
+        data.extend_from_slice(&(1u64 << 32).to_le_bytes()); // data_size
+        data.extend_from_slice(&0x1_0000_0000u64.to_le_bytes()); // sample_count
+        data.extend_from_slice(&1u32.to_le_bytes()); // table_length
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&(1u64 << 32).to_le_bytes());
+
+        let (rest, ds64) = parse_ds64(&data, data.len()).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(ds64.riff_size, 1 << 33);
+        assert_eq!(ds64.data_size, 1 << 32);
+        assert_eq!(ds64.sample_count, 0x1_0000_0000);
+        assert_eq!(ds64.table, vec![(*b"data", 1 << 32)]);
+    }
+}
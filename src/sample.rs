@@ -0,0 +1,179 @@
+//! Typed (de)interleaving of raw PCM packet bytes into per-sample values.
+//!
+//! The demuxer and muxer otherwise deal in raw `data` chunk bytes, leaving
+//! callers to work out the byte layout for a given bit depth themselves. The
+//! functions here pack and unpack samples according to the `(bits,
+//! byte_width)` matrix WAV actually uses in the wild.
+
+use std::error::Error;
+use std::fmt;
+
+/// A single decoded PCM sample, signed regardless of how it is stored on
+/// disk (8-bit samples are stored as unsigned offset-binary bytes).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sample {
+    I8(i8),
+    I16(i16),
+    /// A 24-bit sample, sign-extended into the low 24 bits of an `i32`.
+    I24(i32),
+    I32(i32),
+    F32(f32),
+    F64(f64),
+}
+
+/// A `(bits, byte_width)` combination this module doesn't know how to frame,
+/// or (when packing) a sample whose variant doesn't match that combination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedSampleFormat {
+    pub bits: u16,
+    pub byte_width: u16,
+}
+
+impl fmt::Display for UnsupportedSampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unsupported sample format: {} bits in a {}-byte frame",
+            self.bits, self.byte_width
+        )
+    }
+}
+
+impl Error for UnsupportedSampleFormat {}
+
+fn unpack_sample(
+    bytes: &[u8],
+    bits: u16,
+    byte_width: u16,
+    is_float: bool,
+) -> Result<Sample, UnsupportedSampleFormat> {
+    match (bits, byte_width, is_float) {
+        (8, 1, false) => Ok(Sample::I8((bytes[0] as i16 - 128) as i8)),
+        (16, 2, false) => Ok(Sample::I16(i16::from_le_bytes([bytes[0], bytes[1]]))),
+        (24, 3, false) => {
+            // Sign-extend the 24-bit value into the top byte of an i32.
+            let sign = if bytes[2] & 0x80 != 0 { 0xFF } else { 0x00 };
+            Ok(Sample::I24(i32::from_le_bytes([
+                bytes[0], bytes[1], bytes[2], sign,
+            ])))
+        }
+        (24, 4, false) => {
+            // The padding byte may be zero or sign fill; sign-extend from
+            // bit 23 either way.
+            let sign = if bytes[2] & 0x80 != 0 { 0xFF } else { 0x00 };
+            Ok(Sample::I24(i32::from_le_bytes([
+                bytes[0], bytes[1], bytes[2], sign,
+            ])))
+        }
+        (32, 4, false) => Ok(Sample::I32(i32::from_le_bytes([
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ]))),
+        (32, 4, true) => Ok(Sample::F32(f32::from_le_bytes([
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ]))),
+        (64, 8, true) => {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(&bytes[..8]);
+            Ok(Sample::F64(f64::from_le_bytes(b)))
+        }
+        _ => Err(UnsupportedSampleFormat { bits, byte_width }),
+    }
+}
+
+fn pack_sample(
+    sample: Sample,
+    bits: u16,
+    byte_width: u16,
+    is_float: bool,
+    out: &mut Vec<u8>,
+) -> Result<(), UnsupportedSampleFormat> {
+    match (bits, byte_width, is_float, sample) {
+        (8, 1, false, Sample::I8(v)) => out.push((v as i16 + 128) as u8),
+        (16, 2, false, Sample::I16(v)) => out.extend_from_slice(&v.to_le_bytes()),
+        // Truncate to exactly three bytes; never round.
+        (24, 3, false, Sample::I24(v)) => out.extend_from_slice(&v.to_le_bytes()[..3]),
+        (24, 4, false, Sample::I24(v)) => out.extend_from_slice(&v.to_le_bytes()),
+        (32, 4, false, Sample::I32(v)) => out.extend_from_slice(&v.to_le_bytes()),
+        (32, 4, true, Sample::F32(v)) => out.extend_from_slice(&v.to_le_bytes()),
+        (64, 8, true, Sample::F64(v)) => out.extend_from_slice(&v.to_le_bytes()),
+        _ => return Err(UnsupportedSampleFormat { bits, byte_width }),
+    }
+    Ok(())
+}
+
+/// Unpacks a buffer of raw PCM bytes (e.g. a `data` chunk packet) into
+/// interleaved samples. Divide the result length by the channel count to
+/// recover per-channel samples.
+pub fn unpack_samples(
+    data: &[u8],
+    bits: u16,
+    byte_width: u16,
+    is_float: bool,
+) -> Result<Vec<Sample>, UnsupportedSampleFormat> {
+    data.chunks_exact(byte_width as usize)
+        .map(|chunk| unpack_sample(chunk, bits, byte_width, is_float))
+        .collect()
+}
+
+/// The inverse of [`unpack_samples`]: packs interleaved samples into raw PCM
+/// bytes ready to write into a `data` chunk.
+pub fn pack_samples(
+    samples: &[Sample],
+    bits: u16,
+    byte_width: u16,
+    is_float: bool,
+) -> Result<Vec<u8>, UnsupportedSampleFormat> {
+    let mut out = Vec::with_capacity(samples.len() * byte_width as usize);
+    for &sample in samples {
+        pack_sample(sample, bits, byte_width, is_float, &mut out)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn i24_in_3_bytes_sign_extends_on_unpack() {
+        // -1 stored as the 24-bit two's complement 0xFFFFFF.
+        let sample = unpack_sample(&[0xFF, 0xFF, 0xFF], 24, 3, false).unwrap();
+        assert_eq!(sample, Sample::I24(-1));
+
+        let sample = unpack_sample(&[0x00, 0x00, 0x40], 24, 3, false).unwrap();
+        assert_eq!(sample, Sample::I24(0x0040_0000));
+    }
+
+    #[test]
+    fn i24_in_3_bytes_truncates_on_pack() {
+        let mut out = Vec::new();
+        pack_sample(Sample::I24(-1), 24, 3, false, &mut out).unwrap();
+        assert_eq!(out, vec![0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn i24_round_trips_through_3_and_4_byte_containers() {
+        for &value in &[0, 1, -1, 0x007F_FFFF, -0x0080_0000_i32] {
+            let sample = Sample::I24(value);
+
+            let mut packed3 = Vec::new();
+            pack_sample(sample, 24, 3, false, &mut packed3).unwrap();
+            assert_eq!(unpack_sample(&packed3, 24, 3, false).unwrap(), sample);
+
+            let mut packed4 = Vec::new();
+            pack_sample(sample, 24, 4, false, &mut packed4).unwrap();
+            assert_eq!(unpack_sample(&packed4, 24, 4, false).unwrap(), sample);
+        }
+    }
+
+    #[test]
+    fn unsupported_format_is_rejected() {
+        assert_eq!(
+            unpack_sample(&[0, 0, 0], 20, 3, false),
+            Err(UnsupportedSampleFormat {
+                bits: 20,
+                byte_width: 3
+            })
+        );
+    }
+}